@@ -1,3 +1,10 @@
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
 use std::time::Duration;
 use std::time::SystemTime;
 
@@ -11,6 +18,7 @@ use static_assertions::const_assert;
 use crate::format_error;
 use crate::Config;
 use crate::Error;
+use crate::SharedPeers;
 use crate::UnixServer;
 use crate::WireguardRelay;
 
@@ -18,21 +26,37 @@ pub(crate) struct Dispatcher {
     poll: Poll,
     wg_relay: WireguardRelay,
     unix_server: UnixServer,
+    num_workers: NonZeroUsize,
+    config_path: PathBuf,
+    command: Arc<AtomicU8>,
 }
 
 impl Dispatcher {
     pub(crate) fn new(config: Config) -> Result<Self, Error> {
+        let num_workers = config.num_workers();
+        let config_path = config.path.clone();
         let mut poll = Poll::new()?;
         let unix_server = UnixServer::new(
             config.unix_socket_path.as_path(),
             UNIX_SERVER_TOKEN,
             &mut poll,
         )?;
-        let wg_relay = WireguardRelay::new(config, UDP_SERVER_TOKEN, &mut poll)?;
+        // With more than one worker every UDP socket is bound with `SO_REUSEPORT`
+        // so that the kernel hashes incoming flows across the workers; a single
+        // worker keeps the original exclusive binding.
+        let wg_relay = WireguardRelay::new(
+            config,
+            UDP_SERVER_TOKEN,
+            &mut poll,
+            num_workers.get() > 1,
+        )?;
         Ok(Self {
             poll,
             wg_relay,
             unix_server,
+            num_workers,
+            config_path,
+            command: Arc::new(AtomicU8::new(COMMAND_NONE)),
         })
     }
 
@@ -40,7 +64,25 @@ impl Dispatcher {
         Ok(Waker::new(self.poll.registry(), WAKE_TOKEN)?)
     }
 
+    /// The command slot shared with the signal handlers.  Because mio permits
+    /// only one `Waker` per `Poll`, a handler publishes the desired command
+    /// here via [`publish_command`] (`COMMAND_SHUTDOWN` on `SIGINT`,
+    /// `COMMAND_RELOAD` on `SIGHUP`) before calling [`Dispatcher::waker`]'s
+    /// waker; `run` reads and clears it when it observes `WAKE_TOKEN`.
+    pub(crate) fn command(&self) -> Arc<AtomicU8> {
+        Arc::clone(&self.command)
+    }
+
     pub(crate) fn run(mut self) -> Result<(), Error> {
+        // Spawn the secondary relay workers.  Each one owns its own `Poll` and its
+        // own UDP socket bound to the same address with `SO_REUSEPORT`, and shares
+        // the peer/session table with this worker so that whichever worker the
+        // kernel delivers a peer's datagram to resolves the same forwarding entry.
+        // The `UnixServer` control socket stays owned by this worker so that CLI
+        // mutations funnel through a single place.  The workers run for the whole
+        // lifetime of the process and are torn down when `run` returns and the
+        // process exits.
+        let _workers = self.spawn_workers()?;
         let mut events = Events::with_capacity(MAX_EVENTS);
         loop {
             events.clear();
@@ -56,7 +98,16 @@ impl Dispatcher {
             self.wg_relay.advance(SystemTime::now());
             for event in events.iter() {
                 let ret = match event.token() {
-                    WAKE_TOKEN => return Ok(()),
+                    WAKE_TOKEN => match self.command.swap(COMMAND_NONE, Ordering::AcqRel) {
+                        // Re-read the config and reconcile peers in place, keeping
+                        // existing sessions and the UDP socket untouched.  Because
+                        // the peer table is shared, the secondary workers observe
+                        // the change too.
+                        COMMAND_RELOAD => self.reload(),
+                        // `COMMAND_SHUTDOWN` or a spurious wake both exit, matching
+                        // the historical "any wake means exit" behaviour.
+                        _ => return Ok(()),
+                    },
                     UDP_SERVER_TOKEN => {
                         if event.is_readable() {
                             self.wg_relay.on_event()
@@ -86,9 +137,82 @@ impl Dispatcher {
             }
         }
     }
+
+    fn reload(&mut self) -> Result<(), Error> {
+        let config = Config::open(self.config_path.as_path())?;
+        self.wg_relay.reload(config)?;
+        Ok(())
+    }
+
+    fn spawn_workers(&self) -> Result<Vec<JoinHandle<Result<(), Error>>>, Error> {
+        let mut handles = Vec::with_capacity(self.num_workers.get() - 1);
+        for index in 1..self.num_workers.get() {
+            let peers = self.wg_relay.shared_peers();
+            let config = self.wg_relay.config().clone();
+            handles.push(
+                thread::Builder::new()
+                    .name(format!("wg-worker-{index}"))
+                    .spawn(move || Worker::new(config, peers).and_then(Worker::run))?,
+            );
+        }
+        Ok(handles)
+    }
+}
+
+/// A secondary relay worker: a `Poll` and a `WireguardRelay` that shares its
+/// peer/session table with the [`Dispatcher`] but owns a private `SO_REUSEPORT`
+/// UDP socket.  Unlike the dispatcher it does not serve the control socket.
+struct Worker {
+    poll: Poll,
+    wg_relay: WireguardRelay,
+}
+
+impl Worker {
+    fn new(config: Config, peers: SharedPeers) -> Result<Self, Error> {
+        let mut poll = Poll::new()?;
+        let wg_relay = WireguardRelay::with_shared(config, UDP_SERVER_TOKEN, &mut poll, peers)?;
+        Ok(Self { poll, wg_relay })
+    }
+
+    fn run(mut self) -> Result<(), Error> {
+        let mut events = Events::with_capacity(MAX_EVENTS);
+        loop {
+            events.clear();
+            let timeout = self.wg_relay.next_event_time().map(|t| {
+                t.duration_since(SystemTime::now())
+                    .unwrap_or(Duration::ZERO)
+            });
+            match self.poll.poll(&mut events, timeout) {
+                Ok(()) => Ok(()),
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => Ok(()),
+                other => other,
+            }?;
+            self.wg_relay.advance(SystemTime::now());
+            for event in events.iter() {
+                let ret = match event.token() {
+                    UDP_SERVER_TOKEN => {
+                        if event.is_readable() {
+                            self.wg_relay.on_event()
+                        } else {
+                            Ok(())
+                        }
+                    }
+                    Token(i) => Err(format_error!("unknown event {}", i)),
+                };
+                if let Err(e) = ret {
+                    error!("worker error: {}", e);
+                }
+            }
+        }
+    }
 }
 
 const MAX_EVENTS: usize = 1024;
+pub(crate) const COMMAND_NONE: u8 = 0;
+// Ordered by precedence: a pending shutdown must never be downgraded to a
+// reload, so it carries the higher value and [`publish_command`] keeps the max.
+pub(crate) const COMMAND_RELOAD: u8 = 1;
+pub(crate) const COMMAND_SHUTDOWN: u8 = 2;
 const WAKE_TOKEN: Token = Token(usize::MAX);
 const UDP_SERVER_TOKEN: Token = Token(1);
 const UNIX_SERVER_TOKEN: Token = Token(2);
@@ -98,3 +222,13 @@ const UNIX_TOKEN_MAX: usize = UNIX_TOKEN_MIN + MAX_UNIX_CLIENTS - 1;
 
 const_assert!(UNIX_TOKEN_MIN <= UNIX_TOKEN_MAX);
 const_assert!(MAX_UNIX_CLIENTS == UNIX_TOKEN_MAX - UNIX_TOKEN_MIN + 1);
+const_assert!(COMMAND_SHUTDOWN > COMMAND_RELOAD);
+
+/// Publish `command` into the slot returned by [`Dispatcher::command`] from a
+/// signal handler without ever downgrading a pending shutdown.  Because
+/// `COMMAND_SHUTDOWN` outranks `COMMAND_RELOAD`, keeping the maximum guarantees
+/// that a requested shutdown always wins even if a reload is signalled right
+/// after it.
+pub(crate) fn publish_command(command: &AtomicU8, new: u8) {
+    command.fetch_max(new, Ordering::AcqRel);
+}
@@ -1,14 +1,15 @@
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::BufWriter;
-use std::io::Write;
+use std::collections::VecDeque;
+use std::io::IoSlice;
+use std::io::IoSliceMut;
 use std::os::fd::AsRawFd;
 use std::os::fd::BorrowedFd;
+use std::os::fd::FromRawFd;
 use std::os::fd::OwnedFd;
+use std::os::fd::RawFd;
+use std::time::Duration;
+use std::time::Instant;
 
-use bincode::error::DecodeError;
 use log::error;
 use mio::event::Event;
 use mio::unix::SourceFd;
@@ -22,6 +23,14 @@ use nix::errno::Errno;
 use nix::fcntl::fcntl;
 use nix::fcntl::FcntlArg;
 use nix::fcntl::OFlag;
+use nix::sys::socket::recvmsg;
+use nix::sys::socket::sendmmsg;
+use nix::sys::socket::sendmsg;
+use nix::sys::socket::ControlMessage;
+use nix::sys::socket::ControlMessageOwned;
+use nix::sys::socket::MsgFlags;
+use nix::sys::socket::MultiHeaders;
+use nix::sys::signal::Signal;
 use nix::sys::wait::waitid;
 use nix::sys::wait::WaitPidFlag;
 use nix::sys::wait::WaitStatus;
@@ -36,18 +45,22 @@ pub(crate) struct IpcServer {
     pid_fds: Vec<PidFd>,
     state: IpcStateMachine,
     finished: HashMap<usize, bool>,
+    last_activity: HashMap<usize, Instant>,
+    timeout: Duration,
 }
 
 impl IpcServer {
-    pub(crate) fn new(fds: Vec<(OwnedFd, OwnedFd, PidFd)>) -> Result<Self, std::io::Error> {
+    pub(crate) fn new(
+        fds: Vec<(OwnedFd, PidFd)>,
+        timeout: Duration,
+    ) -> Result<Self, std::io::Error> {
         let poll = Poll::new()?;
         let mut clients = Vec::with_capacity(fds.len());
         let mut pid_fds = Vec::with_capacity(fds.len());
-        for (i, (in_fd, out_fd, pid_fd)) in fds.into_iter().enumerate() {
-            fcntl(in_fd.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
-            fcntl(out_fd.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+        for (i, (socket, pid_fd)) in fds.into_iter().enumerate() {
+            fcntl(socket.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
             poll.registry().register(
-                &mut SourceFd(&in_fd.as_raw_fd()),
+                &mut SourceFd(&socket.as_raw_fd()),
                 fd_in_token(i),
                 Interest::READABLE,
             )?;
@@ -56,16 +69,20 @@ impl IpcServer {
                 pid_fd_token(i),
                 Interest::READABLE,
             )?;
-            clients.push(IpcClient::new(in_fd, out_fd));
+            clients.push(IpcClient::new(socket));
             pid_fds.push(pid_fd);
         }
         let num_nodes = clients.len();
+        let now = Instant::now();
+        let last_activity = (0..num_nodes).map(|i| (i, now)).collect();
         Ok(Self {
             poll,
             clients,
             pid_fds,
             state: IpcStateMachine::new(num_nodes),
             finished: Default::default(),
+            last_activity,
+            timeout,
         })
     }
 
@@ -78,18 +95,27 @@ impl IpcServer {
         let n = self.clients.len();
         while self.finished.len() != n {
             events.clear();
-            match self.poll.poll(&mut events, None) {
+            // Wake up no later than the nearest per-node inactivity deadline so
+            // that a child which stops producing IPC messages without closing
+            // its socket or exiting cannot wedge the whole orchestration.
+            let timeout = self.poll_timeout();
+            match self.poll.poll(&mut events, timeout) {
                 Ok(()) => Ok(()),
                 Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => Ok(()),
                 other => other,
             }?;
+            // Drain the ready events first so that a node which resumes right at
+            // its deadline refreshes `last_activity` (or is recorded as
+            // finished) before the timeout check below, and its final datagram
+            // is not discarded.
             for event in events.iter() {
                 let ret = match event.token() {
                     WAKE_TOKEN => return Ok(()),
                     token @ Token(i) if (0..(NUM_FDS * n)).contains(&i) => {
                         let i = token_to_client_index(token);
+                        self.last_activity.insert(i, Instant::now());
                         match FdKind::new(token) {
-                            FdKind::In | FdKind::Out => self.on_event(event, token, i),
+                            FdKind::Socket => self.on_event(event, token, i),
                             FdKind::Pid => {
                                 self.handle_finished(event, i);
                                 if self.process_failed(i)? {
@@ -105,10 +131,44 @@ impl IpcServer {
                     error!("ipc server error: {}", e);
                 }
             }
+            if let Some(i) = self.timed_out_node() {
+                error!("node {i} timed out");
+                self.finished.insert(i, false);
+                self.kill_node(i)?;
+                return Err(std::io::Error::other(format!("node {i} timed out")));
+            }
         }
         Ok(())
     }
 
+    /// The poll timeout: the smallest remaining inactivity budget across the
+    /// nodes that have not finished yet, or `None` when every node is done.
+    fn poll_timeout(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.last_activity
+            .iter()
+            .filter(|(i, _)| !self.finished.contains_key(i))
+            .map(|(_, last)| self.timeout.saturating_sub(now.duration_since(*last)))
+            .min()
+    }
+
+    /// The index of a live node that has been inactive for longer than the
+    /// configured deadline, if any.
+    fn timed_out_node(&self) -> Option<usize> {
+        let now = Instant::now();
+        self.last_activity
+            .iter()
+            .filter(|(i, _)| !self.finished.contains_key(i))
+            .find(|(_, last)| now.duration_since(**last) >= self.timeout)
+            .map(|(i, _)| *i)
+    }
+
+    fn kill_node(&self, i: usize) -> Result<(), std::io::Error> {
+        let pidfd = unsafe { BorrowedFd::borrow_raw(self.pid_fds[i].as_raw_fd()) };
+        pidfd_send_signal(pidfd, Signal::SIGKILL)?;
+        Ok(())
+    }
+
     fn handle_finished(&mut self, event: &Event, i: usize) {
         if event.is_error() {
             self.finished.insert(i, false);
@@ -121,38 +181,39 @@ impl IpcServer {
     fn on_event(
         &mut self,
         event: &Event,
-        writer_token: Token,
+        socket_token: Token,
         i: usize,
     ) -> Result<(), std::io::Error> {
         self.handle_finished(event, i);
         let mut interest: Option<Interest> = None;
         if event.is_readable() {
-            self.clients[i].fill_buf()?;
-            while let Some(message) = self.clients[i].receive()? {
+            while let Some((message, fds)) = self.clients[i].receive()? {
                 self.state
-                    .on_message(message, i, &mut self.clients, writer_token, &mut self.poll)
+                    .on_message(message, fds, i, &mut self.clients, socket_token, &mut self.poll)
                     .map_err(std::io::Error::other)?;
             }
             if !self.clients[i].flush()? {
                 interest = Some(Interest::WRITABLE);
             }
         }
-        let client = &mut self.clients[i];
-        if event.is_writable() && client.flush()? {
+        if event.is_writable() && self.clients[i].flush()? {
             interest = Some(Interest::READABLE);
         }
+        let client = &mut self.clients[i];
+        // The datagram socket is a single fd, so readiness is always re-armed for
+        // reading; only the extra writable interest is toggled as the send queue
+        // fills and drains.
         match interest {
-            Some(Interest::READABLE) => self
-                .poll
-                .registry()
-                .deregister(&mut SourceFd(&client.writer.get_ref().as_raw_fd()))?,
-            Some(Interest::WRITABLE) => {
-                self.poll.registry().reregister(
-                    &mut SourceFd(&client.writer.get_ref().as_raw_fd()),
-                    writer_token,
-                    Interest::WRITABLE,
-                )?;
-            }
+            Some(Interest::READABLE) => self.poll.registry().reregister(
+                &mut SourceFd(&client.socket.as_raw_fd()),
+                socket_token,
+                Interest::READABLE,
+            )?,
+            Some(Interest::WRITABLE) => self.poll.registry().reregister(
+                &mut SourceFd(&client.socket.as_raw_fd()),
+                socket_token,
+                Interest::READABLE | Interest::WRITABLE,
+            )?,
             _ => {}
         }
         Ok(())
@@ -185,53 +246,196 @@ impl IpcServer {
 }
 
 pub(crate) struct IpcClient {
-    reader: BufReader<File>,
-    writer: BufWriter<File>,
+    socket: OwnedFd,
+    send_queue: VecDeque<OutgoingDatagram>,
+    recv_buf: Vec<u8>,
+    cmsg_buf: Vec<u8>,
+}
+
+/// A queued outgoing datagram together with the file descriptors to pass along
+/// with it.  Descriptors are duplicated into owned copies when queued so that
+/// the datagram can still be sent after the caller's borrow has ended.
+struct OutgoingDatagram {
+    data: Vec<u8>,
+    fds: Vec<OwnedFd>,
 }
 
 impl IpcClient {
-    pub(crate) fn new(in_fd: OwnedFd, out_fd: OwnedFd) -> Self {
+    pub(crate) fn new(socket: OwnedFd) -> Self {
         Self {
-            reader: BufReader::with_capacity(MAX_MESSAGE_SIZE, in_fd.into()),
-            writer: BufWriter::with_capacity(MAX_MESSAGE_SIZE, out_fd.into()),
+            socket,
+            send_queue: VecDeque::new(),
+            recv_buf: vec![0u8; MAX_MESSAGE_SIZE],
+            cmsg_buf: nix::cmsg_space!([RawFd; MAX_FDS]),
         }
     }
 
-    pub(crate) fn fill_buf(&mut self) -> Result<(), std::io::Error> {
-        self.reader.fill_buf()?;
-        Ok(())
-    }
-
-    pub(crate) fn flush(&mut self) -> Result<bool, std::io::Error> {
-        self.writer.flush()?;
-        Ok(self.writer.buffer().is_empty())
+    /// Receive one datagram, if any is ready.  Returns the decoded message and
+    /// any file descriptors the peer attached via `SCM_RIGHTS`.  Each
+    /// `IpcMessage` occupies exactly one `SOCK_SEQPACKET` datagram, so there is
+    /// no partial-frame bookkeeping; a datagram (or its ancillary data) that is
+    /// too large for the reusable buffers is reported as an error rather than
+    /// decoded from a truncated prefix.
+    pub(crate) fn receive(&mut self) -> Result<Option<(IpcMessage, Vec<OwnedFd>)>, std::io::Error> {
+        let (bytes, fds) = {
+            let mut iov = [IoSliceMut::new(&mut self.recv_buf)];
+            let msg = match recvmsg::<()>(
+                self.socket.as_raw_fd(),
+                &mut iov,
+                Some(&mut self.cmsg_buf),
+                MsgFlags::MSG_CMSG_CLOEXEC,
+            ) {
+                Ok(msg) => msg,
+                Err(Errno::EAGAIN) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            if msg.bytes == 0 {
+                // Peer closed the socket.
+                return Ok(None);
+            }
+            // Always wrap the descriptors the kernel delivered so they are
+            // closed on drop, even on the truncation error path below.
+            let mut fds = Vec::new();
+            for cmsg in msg.cmsgs() {
+                if let ControlMessageOwned::ScmRights(raw_fds) = cmsg {
+                    for raw in raw_fds {
+                        fds.push(unsafe { OwnedFd::from_raw_fd(raw) });
+                    }
+                }
+            }
+            // A set `MSG_TRUNC`/`MSG_CTRUNC` flag means the datagram or its
+            // ancillary data was larger than our buffers and the kernel
+            // discarded the tail; the prefix that remains cannot be decoded.
+            // `fds` is dropped here, closing any descriptors that did arrive.
+            if msg.flags.intersects(MsgFlags::MSG_TRUNC | MsgFlags::MSG_CTRUNC) {
+                return Err(std::io::Error::other(format!(
+                    "ipc message exceeds {MAX_MESSAGE_SIZE} bytes"
+                )));
+            }
+            (msg.bytes, fds)
+        };
+        let mut reader = &self.recv_buf[..bytes];
+        let message = IpcMessage::decode(&mut reader).map_err(std::io::Error::other)?;
+        Ok(Some((message, fds)))
     }
 
-    pub(crate) fn receive(&mut self) -> Result<Option<IpcMessage>, std::io::Error> {
-        match IpcMessage::decode(&mut self.reader) {
-            Ok(message) => Ok(Some(message)),
-            Err(DecodeError::UnexpectedEnd { .. }) => Ok(None),
-            Err(e) => Err(std::io::Error::other(e)),
-        }
+    pub(crate) fn send(&mut self, message: &IpcMessage) -> Result<(), std::io::Error> {
+        self.send_with_fds(message, &[])
     }
 
-    pub(crate) fn send(&mut self, message: &IpcMessage) -> Result<(), std::io::Error> {
+    /// Queue `message` for delivery as a single datagram, attaching `fds` via
+    /// `SCM_RIGHTS`, then attempt to drain the send queue.
+    pub(crate) fn send_with_fds(
+        &mut self,
+        message: &IpcMessage,
+        fds: &[BorrowedFd],
+    ) -> Result<(), std::io::Error> {
+        let mut data = Vec::new();
         message
-            .encode(&mut self.writer)
+            .encode(&mut data)
             .map_err(std::io::Error::other)?;
+        let fds = fds
+            .iter()
+            .map(|fd| fd.try_clone_to_owned())
+            .collect::<Result<Vec<_>, _>>()?;
+        self.send_queue.push_back(OutgoingDatagram { data, fds });
+        self.flush()?;
         Ok(())
     }
 
+    /// Drain the send queue, coalescing a burst of queued datagrams into a
+    /// single `sendmmsg` so that many small messages cost one syscall instead of
+    /// one each.  Returns `true` once the queue is empty; `false` means the
+    /// socket was not ready for more and the caller should re-arm
+    /// [`Interest::WRITABLE`].
+    pub(crate) fn flush(&mut self) -> Result<bool, std::io::Error> {
+        while !self.send_queue.is_empty() {
+            // Datagrams carrying file descriptors are sent individually, since
+            // `SCM_RIGHTS` ancillary data is attached per message.
+            if !self.send_queue[0].fds.is_empty() {
+                let datagram = self.send_queue.pop_front().expect("queue is non-empty");
+                if !self.send_datagram(&datagram)? {
+                    self.send_queue.push_front(datagram);
+                    return Ok(false);
+                }
+                continue;
+            }
+            // Coalesce the leading run of plain datagrams into one syscall.
+            let batch = self
+                .send_queue
+                .iter()
+                .take_while(|datagram| datagram.fds.is_empty())
+                .count();
+            let sent = {
+                let iovs: Vec<[IoSlice; 1]> = self
+                    .send_queue
+                    .iter()
+                    .take(batch)
+                    .map(|datagram| [IoSlice::new(datagram.data.as_slice())])
+                    .collect();
+                let mut headers = MultiHeaders::<()>::preallocate(iovs.len(), None);
+                // `sendmmsg` pairs each datagram with one address slot, so there
+                // must be a (connected, hence empty) slot for every datagram.
+                let addrs: Vec<Option<()>> = vec![None; iovs.len()];
+                let cmsgs: [ControlMessage; 0] = [];
+                match sendmmsg(
+                    self.socket.as_raw_fd(),
+                    &mut headers,
+                    &iovs,
+                    addrs,
+                    cmsgs,
+                    MsgFlags::empty(),
+                ) {
+                    // `MultiResults` yields exactly one item per datagram the
+                    // kernel accepted (the `sendmmsg(2)` return value), not one
+                    // per preallocated header, so its length is the number of
+                    // datagrams we may safely dequeue.
+                    Ok(results) => results.count(),
+                    Err(Errno::EAGAIN) => 0,
+                    Err(e) => return Err(e.into()),
+                }
+            };
+            for _ in 0..sent {
+                self.send_queue.pop_front();
+            }
+            if sent < batch {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn send_datagram(&self, datagram: &OutgoingDatagram) -> Result<bool, std::io::Error> {
+        let iov = [IoSlice::new(&datagram.data)];
+        let raw_fds: Vec<RawFd> = datagram.fds.iter().map(|fd| fd.as_raw_fd()).collect();
+        let cmsgs = if raw_fds.is_empty() {
+            Vec::new()
+        } else {
+            vec![ControlMessage::ScmRights(&raw_fds)]
+        };
+        match sendmsg::<()>(
+            self.socket.as_raw_fd(),
+            &iov,
+            &cmsgs,
+            MsgFlags::empty(),
+            None,
+        ) {
+            Ok(_) => Ok(true),
+            Err(Errno::EAGAIN) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     pub(crate) fn send_finalize(
         &mut self,
-        writer_token: Token,
+        socket_token: Token,
         poll: &mut Poll,
     ) -> Result<(), std::io::Error> {
         if !self.flush()? {
             poll.registry().reregister(
-                &mut SourceFd(&self.writer.get_ref().as_raw_fd()),
-                writer_token,
-                Interest::WRITABLE,
+                &mut SourceFd(&self.socket.as_raw_fd()),
+                socket_token,
+                Interest::READABLE | Interest::WRITABLE,
             )?;
         }
         Ok(())
@@ -243,7 +447,7 @@ fn fd_in_token(i: usize) -> Token {
 }
 
 fn pid_fd_token(i: usize) -> Token {
-    Token(NUM_FDS * i + 2)
+    Token(NUM_FDS * i + 1)
 }
 
 fn token_to_client_index(token: Token) -> usize {
@@ -251,21 +455,37 @@ fn token_to_client_index(token: Token) -> usize {
 }
 
 enum FdKind {
-    In,
-    Out,
+    Socket,
     Pid,
 }
 
 impl FdKind {
     fn new(token: Token) -> Self {
         match token.0 % NUM_FDS {
-            0 => Self::In,
-            1 => Self::Out,
+            0 => Self::Socket,
             _ => Self::Pid,
         }
     }
 }
 
+/// Safe wrapper around `pidfd_send_signal(2)`, which `nix` does not yet expose:
+/// sends `signal` to the process referred to by `pidfd`.
+fn pidfd_send_signal(pidfd: BorrowedFd, signal: Signal) -> Result<(), Errno> {
+    // SAFETY: `pidfd` is a valid borrowed file descriptor for the duration of
+    // the call; the `siginfo` and `flags` arguments are left null/zero as the
+    // man page permits.
+    let ret = unsafe {
+        nix::libc::syscall(
+            nix::libc::SYS_pidfd_send_signal,
+            pidfd.as_raw_fd(),
+            signal as nix::libc::c_int,
+            std::ptr::null_mut::<nix::libc::siginfo_t>(),
+            0 as nix::libc::c_uint,
+        )
+    };
+    Errno::result(ret).map(drop)
+}
+
 fn status_is_failure(status: WaitStatus) -> bool {
     match status {
         WaitStatus::Exited(_, code) if code != 0 => true,
@@ -275,5 +495,140 @@ fn status_is_failure(status: WaitStatus) -> bool {
 }
 
 const WAKE_TOKEN: Token = Token(usize::MAX);
-const NUM_FDS: usize = 3;
+const NUM_FDS: usize = 2;
+/// Maximum number of file descriptors that may be passed along with a single
+/// datagram via `SCM_RIGHTS`.
+const MAX_FDS: usize = 8;
+/// Default for the per-node inactivity deadline passed to [`IpcServer::new`]:
+/// how long a node may go without producing an IPC message before the server
+/// considers it hung and kills it.
+pub(crate) const DEFAULT_NODE_INACTIVITY_TIMEOUT: Duration = Duration::from_secs(60);
 pub(crate) const MAX_MESSAGE_SIZE: usize = 4096 * 16;
+
+#[cfg(test)]
+mod tests {
+    use std::os::fd::AsFd;
+
+    use nix::sys::socket::socketpair;
+    use nix::sys::socket::AddressFamily;
+    use nix::sys::socket::SockFlag;
+    use nix::sys::socket::SockType;
+
+    use super::*;
+
+    fn seqpacket_pair() -> (OwnedFd, OwnedFd) {
+        socketpair(
+            AddressFamily::Unix,
+            SockType::SeqPacket,
+            None,
+            SockFlag::SOCK_NONBLOCK,
+        )
+        .expect("socketpair")
+    }
+
+    /// Receive one datagram directly from `fd`, returning its payload and any
+    /// descriptors passed via `SCM_RIGHTS`, or `None` when nothing is ready.
+    fn recv_datagram(fd: BorrowedFd) -> Option<(Vec<u8>, Vec<OwnedFd>)> {
+        let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+        let (bytes, fds) = {
+            let mut iov = [IoSliceMut::new(&mut buf)];
+            let mut cmsg = nix::cmsg_space!([RawFd; MAX_FDS]);
+            let msg = match recvmsg::<()>(
+                fd.as_raw_fd(),
+                &mut iov,
+                Some(&mut cmsg),
+                MsgFlags::MSG_CMSG_CLOEXEC,
+            ) {
+                Ok(msg) => msg,
+                Err(Errno::EAGAIN) => return None,
+                Err(e) => panic!("recvmsg: {e}"),
+            };
+            let mut fds = Vec::new();
+            for cmsg in msg.cmsgs() {
+                if let ControlMessageOwned::ScmRights(raw_fds) = cmsg {
+                    for raw in raw_fds {
+                        fds.push(unsafe { OwnedFd::from_raw_fd(raw) });
+                    }
+                }
+            }
+            (msg.bytes, fds)
+        };
+        Some((buf[..bytes].to_vec(), fds))
+    }
+
+    #[test]
+    fn scm_rights_round_trip() {
+        let (a, b) = seqpacket_pair();
+        let mut client = IpcClient::new(a);
+        // Pass one end of an unrelated socket pair across the channel.
+        let (passed, _peer) = seqpacket_pair();
+        let payload = b"hello".to_vec();
+        client.send_queue.push_back(OutgoingDatagram {
+            data: payload.clone(),
+            fds: vec![passed.try_clone().unwrap()],
+        });
+        assert!(client.flush().unwrap());
+        assert!(client.send_queue.is_empty());
+        let (data, fds) = recv_datagram(b.as_fd()).expect("datagram");
+        assert_eq!(data, payload);
+        assert_eq!(fds.len(), 1);
+        // The descriptor is a fresh one referring to the same open file.
+        assert_ne!(fds[0].as_raw_fd(), passed.as_raw_fd());
+    }
+
+    #[test]
+    fn coalesced_flush_preserves_boundaries() {
+        let (a, b) = seqpacket_pair();
+        let mut client = IpcClient::new(a);
+        for i in 0..4u8 {
+            client.send_queue.push_back(OutgoingDatagram {
+                data: vec![i; 8],
+                fds: Vec::new(),
+            });
+        }
+        assert!(client.flush().unwrap());
+        assert!(client.send_queue.is_empty());
+        for i in 0..4u8 {
+            let (data, fds) = recv_datagram(b.as_fd()).expect("datagram");
+            assert_eq!(data, vec![i; 8]);
+            assert!(fds.is_empty());
+        }
+    }
+
+    #[test]
+    fn flush_requeues_on_short_send_without_loss() {
+        use nix::sys::socket::setsockopt;
+        use nix::sys::socket::sockopt;
+
+        let (a, b) = seqpacket_pair();
+        // A tiny send buffer plus a peer that is not yet reading forces the
+        // kernel to accept only some of the queued datagrams.
+        setsockopt(&a, sockopt::SndBuf, &4096usize).unwrap();
+        let mut client = IpcClient::new(a);
+        let total = 64usize;
+        for _ in 0..total {
+            client.send_queue.push_back(OutgoingDatagram {
+                data: vec![0u8; 2048],
+                fds: Vec::new(),
+            });
+        }
+        let drained = client.flush().unwrap();
+        assert!(!drained, "expected a short send to leave datagrams queued");
+        assert!(!client.send_queue.is_empty());
+        // Alternately drain the peer and re-flush; every datagram must arrive
+        // exactly once and none may be dropped by the requeue logic.
+        let mut received = 0usize;
+        let mut done = false;
+        while !done {
+            while recv_datagram(b.as_fd()).is_some() {
+                received += 1;
+            }
+            done = client.flush().unwrap();
+        }
+        while recv_datagram(b.as_fd()).is_some() {
+            received += 1;
+        }
+        assert_eq!(received, total);
+        assert!(client.send_queue.is_empty());
+    }
+}